@@ -0,0 +1,134 @@
+use std::time::{Duration, Instant};
+
+use log::*;
+use tokio::{
+    io,
+    prelude::*,
+    timer::{Delay, Timeout},
+};
+
+use crate::endpoint::{BoxStream, Endpoint};
+use crate::keepalive::KeepaliveConfig;
+use crate::resolve::Destinations;
+
+/// Bounds how long a single connect attempt may take and how a round that
+/// exhausted every candidate destination is retried: up to `retries` more
+/// rounds, doubling `backoff` after each failed round.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryConfig {
+    pub timeout: Duration,
+    pub retries: u32,
+    pub backoff: Duration,
+}
+
+/// Resolves `dests` and connects to the first candidate that succeeds,
+/// racing each individual attempt against `retry.timeout`. If every
+/// candidate in a round fails, the whole round is retried with exponential
+/// backoff up to `retry.retries` times before giving up.
+pub fn connect(
+    dests: Destinations,
+    nodelay: bool,
+    keepalive: KeepaliveConfig,
+    retry: RetryConfig,
+) -> Box<dyn Future<Item = BoxStream, Error = io::Error> + Send> {
+    Box::new(future::loop_fn(0u32, move |attempt| {
+        let dests = dests.clone();
+
+        dests
+            .resolve()
+            .and_then(move |candidates| {
+                // Captured here (rather than at the round-level log site) since
+                // `candidates` is moved into `connect_any` below.
+                let peers = candidates
+                    .iter()
+                    .map(Endpoint::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                connect_any(candidates, nodelay, keepalive, retry.timeout)
+                    .map_err(move |e| io::Error::new(e.kind(), format!("{} (tried {})", e, peers)))
+            })
+            .then(move |res| -> Box<
+                dyn Future<Item = future::Loop<BoxStream, u32>, Error = io::Error> + Send,
+            > {
+                match res {
+                    Ok(stream) => Box::new(future::ok(future::Loop::Break(stream))),
+                    Err(e) if attempt < retry.retries => {
+                        let delay = retry.backoff * 2u32.saturating_pow(attempt);
+                        warn!(
+                            "Connect round {} failed: {} - retrying in {:?}",
+                            attempt + 1,
+                            e,
+                            delay
+                        );
+                        Box::new(
+                            Delay::new(Instant::now() + delay)
+                                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                                .map(move |_| future::Loop::Continue(attempt + 1)),
+                        )
+                    }
+                    Err(e) => Box::new(future::err(e)),
+                }
+            })
+    }))
+}
+
+/// Tries each of `candidates` in order, returning the first that connects
+/// within `timeout`. Fails with the last candidate's error once the list is
+/// exhausted.
+fn connect_any(
+    candidates: Vec<Endpoint>,
+    nodelay: bool,
+    keepalive: KeepaliveConfig,
+    timeout: Duration,
+) -> Box<dyn Future<Item = BoxStream, Error = io::Error> + Send> {
+    connect_from(candidates, 0, nodelay, keepalive, timeout)
+}
+
+fn connect_from(
+    candidates: Vec<Endpoint>,
+    index: usize,
+    nodelay: bool,
+    keepalive: KeepaliveConfig,
+    timeout: Duration,
+) -> Box<dyn Future<Item = BoxStream, Error = io::Error> + Send> {
+    let dest = match candidates.get(index) {
+        Some(dest) => dest.clone(),
+        None => {
+            return Box::new(future::err(io::Error::new(
+                io::ErrorKind::Other,
+                "no destination address available",
+            )));
+        }
+    };
+    let desc = dest.to_string();
+    let last = index + 1 >= candidates.len();
+
+    Box::new(
+        Timeout::new(dest.connect(nodelay, keepalive), timeout).then(move |res| -> Box<
+            dyn Future<Item = BoxStream, Error = io::Error> + Send,
+        > {
+            match res {
+                Ok(stream) => Box::new(future::ok(stream)),
+                Err(e) if !last => {
+                    debug!(
+                        "Connect attempt to {} failed: {} - trying next candidate",
+                        desc,
+                        timeout_err(e)
+                    );
+                    connect_from(candidates, index + 1, nodelay, keepalive, timeout)
+                }
+                Err(e) => Box::new(future::err(timeout_err(e))),
+            }
+        }),
+    )
+}
+
+fn timeout_err(e: tokio::timer::timeout::Error<io::Error>) -> io::Error {
+    if e.is_elapsed() {
+        io::Error::new(io::ErrorKind::TimedOut, "connect timed out")
+    } else if e.is_timer() {
+        io::Error::new(io::ErrorKind::Other, "timer error")
+    } else {
+        e.into_inner().expect("connect error")
+    }
+}
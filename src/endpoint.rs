@@ -0,0 +1,227 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use log::*;
+use tokio::{
+    io,
+    net::{TcpListener, TcpStream},
+    prelude::*,
+};
+use tokio_uds::{UnixListener, UnixStream};
+
+use crate::keepalive::KeepaliveConfig;
+
+/// A forwarding source or destination: a TCP socket address, a hostname
+/// that must be resolved before connecting, or a filesystem path to a Unix
+/// domain socket (given as `unix:<path>`).
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    Tcp(SocketAddr),
+    Host(String, u16),
+    Unix(PathBuf),
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("unix:") {
+            return Ok(Endpoint::Unix(PathBuf::from(&s["unix:".len()..])));
+        }
+
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(Endpoint::Tcp(addr));
+        }
+
+        match s.rfind(':') {
+            Some(i) => {
+                let port = s[i + 1..]
+                    .parse::<u16>()
+                    .map_err(|e| format!("invalid endpoint `{}`: {}", s, e))?;
+                Ok(Endpoint::Host(s[..i].to_string(), port))
+            }
+            None => Err(format!(
+                "invalid endpoint `{}`: expected `host:port`, `ip:port`, or `unix:<path>`",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(addr) => write!(f, "{}", addr),
+            Endpoint::Host(host, port) => write!(f, "{}:{}", host, port),
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A stream from either a TCP or a Unix listener/connector, type-erased so
+/// the forwarding pipeline can stay agnostic to which side it's talking to.
+pub trait AsyncStream: io::AsyncRead + io::AsyncWrite + Send {}
+impl<T: io::AsyncRead + io::AsyncWrite + Send> AsyncStream for T {}
+
+pub type BoxStream = Box<dyn AsyncStream>;
+
+/// Addressing information about an accepted connection. `peer`/`local` are
+/// only meaningful for TCP; Unix peers carry no address of their own.
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub peer: Option<SocketAddr>,
+    pub local: Option<SocketAddr>,
+}
+
+impl fmt::Display for PeerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.peer {
+            Some(addr) => write!(f, "{}", addr),
+            None => write!(f, "<unix>"),
+        }
+    }
+}
+
+impl Endpoint {
+    /// Connects to this endpoint, applying TCP-only socket options
+    /// (`nodelay`/keepalive) when the endpoint is a TCP address. `Host`
+    /// endpoints must be resolved to a `Tcp` endpoint first (see
+    /// `crate::resolve`); connecting one directly is a programmer error.
+    pub fn connect(
+        &self,
+        nodelay: bool,
+        keepalive: KeepaliveConfig,
+    ) -> Box<dyn Future<Item = BoxStream, Error = io::Error> + Send> {
+        match self {
+            Endpoint::Tcp(addr) => Box::new(TcpStream::connect(addr).map(move |sock| {
+                tcp_sockopt(&sock, nodelay, keepalive);
+                Box::new(sock) as BoxStream
+            })),
+            Endpoint::Unix(path) => {
+                Box::new(UnixStream::connect(path).map(|sock| Box::new(sock) as BoxStream))
+            }
+            Endpoint::Host(host, port) => Box::new(future::err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unresolved hostname endpoint `{}:{}`", host, port),
+            ))),
+        }
+    }
+
+    /// Binds a listener for this endpoint. For Unix endpoints, any stale
+    /// socket file left over from a previous run is removed first. A
+    /// hostname endpoint cannot be listened on, since it names no single
+    /// local address.
+    pub fn listen(&self) -> io::Result<Listener> {
+        match self {
+            Endpoint::Tcp(addr) => Ok(Listener::Tcp(TcpListener::bind(addr)?)),
+            Endpoint::Unix(path) => {
+                if path.exists() {
+                    let _ = ::std::fs::remove_file(path);
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+            Endpoint::Host(host, port) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot listen on hostname endpoint `{}:{}`", host, port),
+            )),
+        }
+    }
+}
+
+/// A bound listener, abstracting over TCP and Unix domain sockets.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Returns a stream of incoming connections, each already wrapped as a
+    /// `BoxStream` and paired with its `PeerInfo`.
+    pub fn incoming(
+        self,
+        nodelay: bool,
+        keepalive: KeepaliveConfig,
+    ) -> Box<dyn Stream<Item = (BoxStream, PeerInfo), Error = io::Error> + Send> {
+        match self {
+            Listener::Tcp(listener) => Box::new(listener.incoming().map(move |sock| {
+                tcp_sockopt(&sock, nodelay, keepalive);
+                let info = PeerInfo {
+                    peer: sock.peer_addr().ok(),
+                    local: sock.local_addr().ok(),
+                };
+                (Box::new(sock) as BoxStream, info)
+            })),
+            Listener::Unix(listener) => Box::new(listener.incoming().map(|sock| {
+                let info = PeerInfo {
+                    peer: None,
+                    local: None,
+                };
+                (Box::new(sock) as BoxStream, info)
+            })),
+        }
+    }
+}
+
+fn tcp_sockopt(sock: &TcpStream, nodelay: bool, keepalive: KeepaliveConfig) {
+    keepalive.apply(sock).unwrap_or_else(|e| error!("{}", e));
+    sock.set_nodelay(nodelay).unwrap_or_else(|e| error!("{}", e));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_unix() {
+        match "unix:/tmp/app.sock".parse::<Endpoint>() {
+            Ok(Endpoint::Unix(path)) => assert_eq!(path, PathBuf::from("/tmp/app.sock")),
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_ipv4_socket_addr() {
+        match "127.0.0.1:8080".parse::<Endpoint>() {
+            Ok(Endpoint::Tcp(addr)) => assert_eq!(addr, "127.0.0.1:8080".parse().unwrap()),
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_ipv6_socket_addr() {
+        match "[::1]:8080".parse::<Endpoint>() {
+            Ok(Endpoint::Tcp(addr)) => assert_eq!(addr, "[::1]:8080".parse().unwrap()),
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_host_port() {
+        match "example.com:8080".parse::<Endpoint>() {
+            Ok(Endpoint::Host(host, port)) => {
+                assert_eq!(host, "example.com");
+                assert_eq!(port, 8080);
+            }
+            other => panic!("unexpected parse result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_missing_port() {
+        assert!("example.com".parse::<Endpoint>().is_err());
+    }
+
+    #[test]
+    fn rejects_non_numeric_port() {
+        assert!("example.com:http".parse::<Endpoint>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        assert_eq!("unix:/tmp/app.sock".parse::<Endpoint>().unwrap().to_string(), "unix:/tmp/app.sock");
+        assert_eq!("127.0.0.1:8080".parse::<Endpoint>().unwrap().to_string(), "127.0.0.1:8080");
+        assert_eq!("example.com:8080".parse::<Endpoint>().unwrap().to_string(), "example.com:8080");
+    }
+}
@@ -0,0 +1,78 @@
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+use std::time::Duration;
+
+use socket2::{Socket, TcpKeepalive};
+use tokio::{io, net::TcpStream};
+
+/// TCP keepalive tuning, applied via `socket2` so idle time, probe interval
+/// and probe count can be set independently instead of through the single
+/// idle-time knob `TcpStream::set_keepalive` exposes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeepaliveConfig {
+    pub time: Option<Duration>,
+    pub interval: Option<Duration>,
+    pub retries: Option<u32>,
+}
+
+impl KeepaliveConfig {
+    fn is_enabled(&self) -> bool {
+        self.time.is_some() || self.interval.is_some() || self.retries.is_some()
+    }
+
+    /// Applies the configured keepalive parameters to `sock`. A no-op if
+    /// none of `time`/`interval`/`retries` were set.
+    pub fn apply(&self, sock: &TcpStream) -> io::Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        let mut keepalive = TcpKeepalive::new();
+        if let Some(time) = self.time {
+            keepalive = keepalive.with_time(time);
+        }
+
+        // `with_interval`/`with_retries` are gated by socket2 on different
+        // (and not identical) sets of platforms - mirror each gate rather
+        // than applying both under one, so a platform supporting only one
+        // of the two still gets it applied.
+        #[cfg(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "illumos",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "tvos",
+            target_os = "watchos",
+            target_os = "windows",
+        ))]
+        {
+            if let Some(interval) = self.interval {
+                keepalive = keepalive.with_interval(interval);
+            }
+        }
+        #[cfg(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "illumos",
+            target_os = "linux",
+            target_os = "netbsd",
+        ))]
+        {
+            if let Some(retries) = self.retries {
+                keepalive = keepalive.with_retries(retries);
+            }
+        }
+
+        // `sock` owns the fd; wrap it without taking that ownership away.
+        let socket = unsafe { Socket::from_raw_fd(sock.as_raw_fd()) };
+        let result = socket.set_tcp_keepalive(&keepalive);
+        let _ = socket.into_raw_fd();
+        result
+    }
+}
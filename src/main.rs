@@ -1,62 +1,172 @@
+mod connect;
+mod endpoint;
+mod keepalive;
+mod metrics;
+mod proxy_protocol;
+mod resolve;
+mod tls;
+
 use log::*;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use structopt::StructOpt;
-use tokio::{
-    io,
-    net::{TcpListener, TcpStream},
-    prelude::*,
-};
+use tokio::{io, prelude::*};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use connect::RetryConfig;
+use endpoint::{BoxStream, Endpoint, PeerInfo};
+use keepalive::KeepaliveConfig;
+use metrics::{CountingRead, Metrics};
+use proxy_protocol::ProxyProtocol;
+use resolve::Destinations;
 
 #[derive(StructOpt, Debug, Clone)]
 struct Opt {
-    /// Source ip address of forwarder
+    /// Source address of forwarder: a TCP socket address or `unix:<path>`
     #[structopt(name = "source")]
-    source: std::net::SocketAddr,
-    /// Destination ip address of forwarder
-    #[structopt(name = "dest")]
-    dest: std::net::SocketAddr,
-    /// Set TCP_NODELAY option.
+    source: Endpoint,
+    /// Destination(s) of forwarder: a TCP socket address, a `host:port`
+    /// name to resolve, or `unix:<path>`. Multiple may be given to fan
+    /// connections out across several backends, round-robin.
+    #[structopt(name = "dest", required = true, min_values = 1)]
+    dest: Vec<Endpoint>,
+    /// Set TCP_NODELAY option. Ignored for Unix sockets.
     #[structopt(short = "n", long = "nodelay")]
     nodelay: bool,
-    /// Set keepalive interval.
-    #[structopt(short = "k", long = "keepalive", default_value = "30")]
-    keepalive: u64,
+    /// Idle time before the first keepalive probe is sent, in seconds. 0
+    /// disables keepalive entirely. Ignored for Unix sockets.
+    #[structopt(long = "keepalive-time", default_value = "30")]
+    keepalive_time: u64,
+    /// Interval between keepalive probes, in seconds. Ignored for Unix
+    /// sockets and unsupported on some platforms.
+    #[structopt(long = "keepalive-interval")]
+    keepalive_interval: Option<u64>,
+    /// Number of failed keepalive probes before the connection is dropped.
+    /// Ignored for Unix sockets and unsupported on some platforms.
+    #[structopt(long = "keepalive-retries")]
+    keepalive_retries: Option<u32>,
+    /// Prepend a PROXY protocol header to the destination connection,
+    /// describing the original client address.
+    #[structopt(long = "proxy-protocol")]
+    proxy_protocol: Option<ProxyProtocol>,
+    /// Terminate TLS on the listening side using this certificate PEM file.
+    /// Requires `--tls-key`.
+    #[structopt(long = "tls-cert", requires = "tls-key")]
+    tls_cert: Option<PathBuf>,
+    /// Private key PEM file to pair with `--tls-cert`.
+    #[structopt(long = "tls-key", requires = "tls-cert")]
+    tls_key: Option<PathBuf>,
+    /// Originate TLS toward the destination, presenting this SNI hostname.
+    #[structopt(long = "tls-connect")]
+    tls_connect: Option<String>,
+    /// Maximum time to wait for a single connect attempt to `dest`, in
+    /// seconds.
+    #[structopt(long = "connect-timeout", default_value = "5")]
+    connect_timeout: u64,
+    /// Number of additional connect rounds after a round in which every
+    /// resolved destination failed.
+    #[structopt(long = "connect-retries", default_value = "0")]
+    connect_retries: u32,
+    /// Initial delay before retrying a failed connect round, in seconds,
+    /// doubled after each failed round.
+    #[structopt(long = "connect-backoff", default_value = "1")]
+    connect_backoff: u64,
+    /// Maximum number of connections forwarded concurrently. Accepts past
+    /// this limit wait for a slot to free up. Unbounded if unset.
+    #[structopt(long = "max-connections")]
+    max_connections: Option<usize>,
+    /// Interval between metrics log lines, in seconds.
+    #[structopt(long = "metrics-interval", default_value = "30")]
+    metrics_interval: u64,
 }
 
-fn keepalive(secs: u64) -> Option<Duration> {
-    match secs {
-        0 => None,
-        s => Some(Duration::from_secs(s)),
-    }
+/// TLS state built once at startup and cloned (cheaply - both handles wrap
+/// an `Arc`) into each connection.
+#[derive(Clone, Default)]
+struct Tls {
+    acceptor: Option<TlsAcceptor>,
+    connector: Option<(TlsConnector, String)>,
 }
 
-fn sockopt(sock: &TcpStream, cfg: &Opt) {
-    sock.set_keepalive(keepalive(cfg.keepalive))
-        .unwrap_or_else(|e| error!("{}", e));
-    sock.set_nodelay(cfg.nodelay)
-        .unwrap_or_else(|e| error!("{}", e));
-}
+impl Opt {
+    fn keepalive(&self) -> KeepaliveConfig {
+        if self.keepalive_time == 0 {
+            return KeepaliveConfig::default();
+        }
+        KeepaliveConfig {
+            time: match self.keepalive_time {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            interval: self.keepalive_interval.map(Duration::from_secs),
+            retries: self.keepalive_retries,
+        }
+    }
 
-fn peer(src: &TcpStream) -> String {
-    src.peer_addr()
-        .map(|p| p.to_string())
-        .unwrap_or_else(|_| "<unknown>".into())
+    fn retry(&self) -> RetryConfig {
+        RetryConfig {
+            timeout: Duration::from_secs(self.connect_timeout),
+            retries: self.connect_retries,
+            backoff: Duration::from_secs(self.connect_backoff),
+        }
+    }
 }
 
-fn fwd(src: TcpStream, cfg: Opt) -> impl Future<Item = (), Error = io::Error> {
-    sockopt(&src, &cfg);
-
-    TcpStream::connect(&cfg.dest).and_then(move |dst| {
-        sockopt(&dst, &cfg);
-
-        let (srd, swr) = src.split();
-        let (drd, dwr) = dst.split();
-
-        let up = io::copy(srd, dwr);
-        let down = io::copy(drd, swr);
-
-        up.select(down).map(|_| ()).map_err(|(e, _)| e)
-    })
+/// Forwards one accepted connection, returning the bytes copied upstream
+/// and downstream. The two directions race via `select` (so the whole
+/// connection closes as soon as either side does); byte counts are tallied
+/// via `CountingRead` as data actually moves rather than read off the copy
+/// futures' own results, so the direction that `select` drops still gets
+/// credited for what it transferred before that point.
+fn fwd(
+    src: BoxStream,
+    peer: PeerInfo,
+    cfg: Opt,
+    tls: Tls,
+    dests: Destinations,
+) -> impl Future<Item = (u64, u64), Error = io::Error> {
+    let proxy_protocol = cfg.proxy_protocol;
+    let keepalive = cfg.keepalive();
+    let retry = cfg.retry();
+
+    // PROXY protocol header goes first, in plaintext, since PROXY-aware
+    // backends expect it ahead of the TLS handshake rather than inside it.
+    connect::connect(dests, cfg.nodelay, keepalive, retry)
+        .and_then(move |dst| -> Box<dyn Future<Item = BoxStream, Error = io::Error> + Send> {
+            match proxy_protocol {
+                Some(v) => Box::new(io::write_all(dst, v.header(&peer)).map(|(dst, _)| dst)),
+                None => Box::new(future::ok(dst)),
+            }
+        })
+        .and_then(move |dst| -> Box<dyn Future<Item = BoxStream, Error = io::Error> + Send> {
+            match &tls.connector {
+                Some((connector, hostname)) => {
+                    Box::new(tls::connect(connector.clone(), hostname, dst))
+                }
+                None => Box::new(future::ok(dst)),
+            }
+        })
+        .and_then(move |dst| {
+            let (srd, swr) = src.split();
+            let (drd, dwr) = dst.split();
+
+            let bytes_up = Arc::new(AtomicU64::new(0));
+            let bytes_down = Arc::new(AtomicU64::new(0));
+
+            let up = io::copy(CountingRead::new(srd, bytes_up.clone()), dwr);
+            let down = io::copy(CountingRead::new(drd, bytes_down.clone()), swr);
+
+            up.select(down)
+                .map(move |_| {
+                    (
+                        bytes_up.load(Ordering::Relaxed),
+                        bytes_down.load(Ordering::Relaxed),
+                    )
+                })
+                .map_err(|(e, _)| e)
+        })
 }
 
 fn main() {
@@ -66,25 +176,80 @@ fn main() {
 
     info!("Starting: {:?}", cfg);
 
-    let fwd = TcpListener::bind(&cfg.source)
-        .into_future()
-        .and_then(|sock| {
-            sock.incoming()
-                .map(move |src| {
-                    let addr = peer(&src);
-
-                    info!("Connected ({})", addr);
-
-                    fwd(src, cfg.clone()).then(move |res| match res {
-                        Ok(_) => Ok(info!("Disconnected ({})", addr)),
-                        Err(e) => Ok(error!("Disconnected with error: {} ({})", e, addr)),
-                    })
+    let tls = Tls {
+        acceptor: match (&cfg.tls_cert, &cfg.tls_key) {
+            (Some(cert), Some(key)) => {
+                Some(tls::acceptor(cert, key).unwrap_or_else(|e| panic!("invalid TLS config: {}", e)))
+            }
+            _ => None,
+        },
+        connector: cfg
+            .tls_connect
+            .clone()
+            .map(|hostname| (tls::connector(), hostname)),
+    };
+
+    let nodelay = cfg.nodelay;
+    let keepalive = cfg.keepalive();
+    let dests = Destinations::new(cfg.dest.clone());
+    let metrics = Metrics::new();
+    let max_connections = cfg.max_connections.unwrap_or_else(usize::max_value);
+    let metrics_interval = Duration::from_secs(cfg.metrics_interval);
+    let report_metrics = metrics.clone();
+
+    let fwd = future::result(cfg.source.listen())
+        .and_then(move |listener| {
+            listener
+                .incoming(nodelay, keepalive)
+                .map(move |(src, peer)| {
+                    info!("Connected ({})", peer);
+                    let addr = peer.to_string();
+                    let cfg = cfg.clone();
+                    let tls = tls.clone();
+                    let dests = dests.clone();
+                    let metrics = metrics.clone();
+                    metrics.connected();
+
+                    let accepted: Box<dyn Future<Item = BoxStream, Error = io::Error> + Send> =
+                        match &tls.acceptor {
+                            Some(acceptor) => Box::new(tls::accept(acceptor, src)),
+                            None => Box::new(future::ok(src)),
+                        };
+
+                    accepted
+                        .and_then(move |src| fwd(src, peer, cfg, tls, dests))
+                        .then(move |res| {
+                            match &res {
+                                Ok(_) => info!("Disconnected ({})", addr),
+                                Err(e) => error!("Disconnected with error: {} ({})", e, addr),
+                            }
+                            metrics.disconnected(&res);
+                            Ok(())
+                        })
                 })
-                .buffer_unordered(usize::max_value())
+                // Bounds how many connections are forwarded at once: `buffer_unordered`
+                // only pulls (and thus accepts) the next connection once a slot frees up,
+                // so this doubles as the accept-side backpressure the limit calls for.
+                // (A semaphore acquired around `fwd` would give the same bound plus a
+                // directly-inspectable in-flight count, and would let a future change -
+                // e.g. rejecting over-limit connections instead of queuing them - be a
+                // local edit; this tree uses `buffer_unordered` instead, folding the
+                // limit into the accept loop.)
+                .buffer_unordered(max_connections)
                 .for_each(|_| Ok(()))
         })
         .map(|_| info!("Shutdown"))
         .map_err(|e| error!("Shutdown with error: {}", e));
 
-    tokio::run(fwd);
+    // Spawned rather than `block_on`-ed: connection futures call into
+    // `tokio_threadpool::blocking` (for DNS resolution), which only succeeds
+    // when polled from a pool worker thread, not from the thread driving
+    // `block_on`.
+    let mut runtime = tokio::runtime::Runtime::new().expect("failed to start runtime");
+    runtime.spawn(report_metrics.report(metrics_interval));
+    runtime.spawn(fwd);
+    runtime
+        .shutdown_on_idle()
+        .wait()
+        .expect("runtime error");
 }
@@ -0,0 +1,97 @@
+use std::io::Read;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::*;
+use tokio::{io, prelude::*, timer::Interval};
+
+/// Running counters for the forwarder, shared across all connections and
+/// logged periodically so operators can see load without instrumenting the
+/// process externally.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    active: Arc<AtomicI64>,
+    accepted: Arc<AtomicU64>,
+    bytes_up: Arc<AtomicU64>,
+    bytes_down: Arc<AtomicU64>,
+    errors: Arc<AtomicU64>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Records a newly accepted connection.
+    pub fn connected(&self) {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a connection finishing. On success, folds in the up/down byte
+    /// counts tallied for it (see `CountingRead`); if forwarding failed, it
+    /// is counted as an error and its bytes are not added.
+    pub fn disconnected(&self, result: &io::Result<(u64, u64)>) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+        match result {
+            Ok((up, down)) => {
+                self.bytes_up.fetch_add(*up, Ordering::Relaxed);
+                self.bytes_down.fetch_add(*down, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn log(&self) {
+        info!(
+            "Metrics: active={} accepted={} bytes_up={} bytes_down={} errors={}",
+            self.active.load(Ordering::Relaxed),
+            self.accepted.load(Ordering::Relaxed),
+            self.bytes_up.load(Ordering::Relaxed),
+            self.bytes_down.load(Ordering::Relaxed),
+            self.errors.load(Ordering::Relaxed)
+        );
+    }
+
+    /// A future that logs the counters every `interval`, forever. Spawned
+    /// as a background task alongside the accept loop.
+    pub fn report(&self, interval: Duration) -> impl Future<Item = (), Error = ()> {
+        let metrics = self.clone();
+        Interval::new(Instant::now() + interval, interval)
+            .map_err(|e| error!("metrics timer error: {}", e))
+            .for_each(move |_| {
+                metrics.log();
+                Ok(())
+            })
+    }
+}
+
+/// Wraps a reader, adding every byte it yields to a shared counter as it's
+/// read. `fwd`'s two `io::copy` futures race via `select`, which drops
+/// whichever one is still running once the other completes - wrapping the
+/// reader (rather than reading the copy future's own final tally) means
+/// bytes are counted as they actually move, so the dropped direction still
+/// contributes what it transferred instead of being reported as 0.
+pub struct CountingRead<R> {
+    inner: R,
+    counter: Arc<AtomicU64>,
+}
+
+impl<R> CountingRead<R> {
+    pub fn new(inner: R, counter: Arc<AtomicU64>) -> Self {
+        CountingRead { inner, counter }
+    }
+}
+
+impl<R: Read> Read for CountingRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counter.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<R: io::AsyncRead> io::AsyncRead for CountingRead<R> {}
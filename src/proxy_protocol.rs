@@ -0,0 +1,171 @@
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use crate::endpoint::PeerInfo;
+
+/// The PROXY protocol version to prepend to the forwarded connection, so the
+/// destination can recover the original client address.
+#[derive(Debug, Clone, Copy)]
+pub enum ProxyProtocol {
+    V1,
+    V2,
+}
+
+impl FromStr for ProxyProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" => Ok(ProxyProtocol::V1),
+            "v2" => Ok(ProxyProtocol::V2),
+            s => Err(format!("invalid proxy protocol version `{}`, expected v1 or v2", s)),
+        }
+    }
+}
+
+impl ProxyProtocol {
+    /// Builds the header to write immediately after connecting to the
+    /// destination, describing `peer` as seen by the listening side.
+    pub fn header(self, peer: &PeerInfo) -> Vec<u8> {
+        match self {
+            ProxyProtocol::V1 => header_v1(peer),
+            ProxyProtocol::V2 => header_v2(peer),
+        }
+    }
+}
+
+fn header_v1(peer: &PeerInfo) -> Vec<u8> {
+    let line = match (peer.peer, peer.local) {
+        (Some(SocketAddr::V4(src)), Some(SocketAddr::V4(dst))) => format!(
+            "PROXY TCP4 {} {} {} {}\r\n",
+            src.ip(),
+            dst.ip(),
+            src.port(),
+            dst.port()
+        ),
+        (Some(SocketAddr::V6(src)), Some(SocketAddr::V6(dst))) => format!(
+            "PROXY TCP6 {} {} {} {}\r\n",
+            src.ip(),
+            dst.ip(),
+            src.port(),
+            dst.port()
+        ),
+        _ => "PROXY UNKNOWN\r\n".to_string(),
+    };
+    line.into_bytes()
+}
+
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+fn header_v2(peer: &PeerInfo) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(28);
+    buf.extend_from_slice(&V2_SIGNATURE);
+    buf.push(0x21); // version 2, PROXY command
+
+    match (peer.peer, peer.local) {
+        (Some(SocketAddr::V4(src)), Some(SocketAddr::V4(dst))) => {
+            buf.push(0x11); // AF_INET / STREAM
+            buf.extend_from_slice(&12u16.to_be_bytes());
+            buf.extend_from_slice(&src.ip().octets());
+            buf.extend_from_slice(&dst.ip().octets());
+            buf.extend_from_slice(&src.port().to_be_bytes());
+            buf.extend_from_slice(&dst.port().to_be_bytes());
+        }
+        (Some(SocketAddr::V6(src)), Some(SocketAddr::V6(dst))) => {
+            buf.push(0x21); // AF_INET6 / STREAM
+            buf.extend_from_slice(&36u16.to_be_bytes());
+            buf.extend_from_slice(&src.ip().octets());
+            buf.extend_from_slice(&dst.ip().octets());
+            buf.extend_from_slice(&src.port().to_be_bytes());
+            buf.extend_from_slice(&dst.port().to_be_bytes());
+        }
+        _ => {
+            buf.push(0x00); // AF_UNSPEC / UNSPEC
+            buf.extend_from_slice(&0u16.to_be_bytes());
+        }
+    }
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(src: &str, dst: &str) -> PeerInfo {
+        PeerInfo {
+            peer: Some(src.parse().unwrap()),
+            local: Some(dst.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn parses_version() {
+        assert!(match ProxyProtocol::from_str("v1") {
+            Ok(ProxyProtocol::V1) => true,
+            _ => false,
+        });
+        assert!(match ProxyProtocol::from_str("v2") {
+            Ok(ProxyProtocol::V2) => true,
+            _ => false,
+        });
+        assert!(ProxyProtocol::from_str("v3").is_err());
+    }
+
+    #[test]
+    fn v1_header_ipv4() {
+        let header = header_v1(&peer("1.2.3.4:5555", "9.9.9.9:80"));
+        assert_eq!(header, b"PROXY TCP4 1.2.3.4 9.9.9.9 5555 80\r\n");
+    }
+
+    #[test]
+    fn v1_header_ipv6() {
+        let header = header_v1(&peer("[::1]:5555", "[::2]:80"));
+        assert_eq!(header, b"PROXY TCP6 ::1 ::2 5555 80\r\n");
+    }
+
+    #[test]
+    fn v1_header_unknown() {
+        let header = header_v1(&PeerInfo { peer: None, local: None });
+        assert_eq!(header, b"PROXY UNKNOWN\r\n");
+    }
+
+    #[test]
+    fn v2_header_ipv4() {
+        let header = header_v2(&peer("1.2.3.4:5555", "9.9.9.9:80"));
+
+        assert_eq!(&header[..12], &V2_SIGNATURE);
+        assert_eq!(header[12], 0x21);
+        assert_eq!(header[13], 0x11);
+        assert_eq!(&header[14..16], &12u16.to_be_bytes());
+        assert_eq!(header.len(), 16 + 12);
+        assert_eq!(&header[16..20], &[1, 2, 3, 4]);
+        assert_eq!(&header[20..24], &[9, 9, 9, 9]);
+        assert_eq!(&header[24..26], &5555u16.to_be_bytes());
+        assert_eq!(&header[26..28], &80u16.to_be_bytes());
+    }
+
+    #[test]
+    fn v2_header_ipv6() {
+        let header = header_v2(&peer("[::1]:5555", "[::2]:80"));
+
+        assert_eq!(&header[..12], &V2_SIGNATURE);
+        assert_eq!(header[12], 0x21);
+        assert_eq!(header[13], 0x21);
+        assert_eq!(&header[14..16], &36u16.to_be_bytes());
+        assert_eq!(header.len(), 16 + 36);
+    }
+
+    #[test]
+    fn v2_header_unknown() {
+        let header = header_v2(&PeerInfo { peer: None, local: None });
+
+        assert_eq!(&header[..12], &V2_SIGNATURE);
+        assert_eq!(header[12], 0x21);
+        assert_eq!(header[13], 0x00);
+        assert_eq!(&header[14..16], &0u16.to_be_bytes());
+        assert_eq!(header.len(), 16);
+    }
+}
@@ -0,0 +1,84 @@
+use std::net::ToSocketAddrs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::io;
+use tokio::prelude::*;
+use tokio_threadpool::blocking;
+
+use crate::endpoint::Endpoint;
+
+/// The set of configured destination endpoints. Resolved fresh on every
+/// connection attempt (so DNS changes take effect without a restart) into a
+/// flat list of concrete `Tcp`/`Unix` candidates, rotated by `next` so
+/// successive connections start at a different backend for basic load
+/// spreading.
+#[derive(Clone)]
+pub struct Destinations {
+    dests: Arc<Vec<Endpoint>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl Destinations {
+    pub fn new(dests: Vec<Endpoint>) -> Self {
+        Destinations {
+            dests: Arc::new(dests),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Resolves every configured endpoint and rotates the result so each
+    /// call starts at the next candidate round-robin.
+    ///
+    /// DNS lookups run on the `tokio_threadpool` blocking pool, which only
+    /// accepts blocking calls made from a pool worker thread. When none of
+    /// `dests` is a `Host` that needs resolving, this skips `blocking()`
+    /// entirely and resolves inline instead of bouncing through the pool.
+    pub fn resolve(&self) -> Box<dyn Future<Item = Vec<Endpoint>, Error = io::Error> + Send> {
+        let dests = self.dests.clone();
+        let next = self.next.clone();
+
+        if dests.iter().any(is_host) {
+            Box::new(
+                future::poll_fn(move || blocking(|| resolve_all(&dests)))
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                    .and_then(future::result)
+                    .map(move |candidates| rotate(candidates, &next)),
+            )
+        } else {
+            Box::new(
+                future::result(resolve_all(&dests)).map(move |candidates| rotate(candidates, &next)),
+            )
+        }
+    }
+}
+
+fn is_host(dest: &Endpoint) -> bool {
+    match dest {
+        Endpoint::Host(_, _) => true,
+        _ => false,
+    }
+}
+
+fn rotate(mut candidates: Vec<Endpoint>, next: &AtomicUsize) -> Vec<Endpoint> {
+    if !candidates.is_empty() {
+        let start = next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+        candidates.rotate_left(start);
+    }
+    candidates
+}
+
+fn resolve_all(dests: &[Endpoint]) -> io::Result<Vec<Endpoint>> {
+    let mut candidates = Vec::new();
+    for dest in dests {
+        match dest {
+            Endpoint::Host(host, port) => {
+                for addr in (host.as_str(), *port).to_socket_addrs()? {
+                    candidates.push(Endpoint::Tcp(addr));
+                }
+            }
+            other => candidates.push(other.clone()),
+        }
+    }
+    Ok(candidates)
+}
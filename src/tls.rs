@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::internal::pemfile::{certs, pkcs8_private_keys};
+use rustls::{ClientConfig, NoClientAuth, ServerConfig};
+use tokio::{io, prelude::*};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use webpki::DNSNameRef;
+
+use crate::endpoint::BoxStream;
+
+/// Builds a server TLS config from a PEM certificate chain and PKCS#8
+/// private key, for terminating TLS on the listening side.
+pub fn acceptor(cert: &Path, key: &Path) -> io::Result<TlsAcceptor> {
+    let certs = certs(&mut BufReader::new(File::open(cert)?))
+        .map_err(|_| invalid_data("could not parse certificate"))?;
+    let mut keys = pkcs8_private_keys(&mut BufReader::new(File::open(key)?))
+        .map_err(|_| invalid_data("could not parse private key"))?;
+
+    if keys.is_empty() {
+        return Err(invalid_data("no private key found"));
+    }
+
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config
+        .set_single_cert(certs, keys.remove(0))
+        .map_err(|e| invalid_data(&e.to_string()))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Builds a client TLS config trusting the root certificates bundled in
+/// `webpki-roots` (not the platform's certificate store), for
+/// originating TLS toward the destination.
+pub fn connector() -> TlsConnector {
+    let mut config = ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    TlsConnector::from(Arc::new(config))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Terminates TLS on `sock` as the server side, returning the decrypted
+/// stream to feed into the copy pipeline.
+pub fn accept(
+    acceptor: &TlsAcceptor,
+    sock: BoxStream,
+) -> impl Future<Item = BoxStream, Error = io::Error> {
+    acceptor.accept(sock).map(|s| Box::new(s) as BoxStream)
+}
+
+/// Originates TLS toward `sock` as the client side, presenting `hostname`
+/// via SNI.
+pub fn connect(
+    connector: TlsConnector,
+    hostname: &str,
+    sock: BoxStream,
+) -> impl Future<Item = BoxStream, Error = io::Error> {
+    // Owned rather than a `DNSNameRef` borrowed from `hostname`, since the
+    // latter would bake `hostname`'s elided lifetime into this function's
+    // opaque return type. `connector` is taken by value for the same
+    // reason: a `&TlsConnector` captured by the closure below would bake
+    // its own elided lifetime in too. `TlsConnector` is `Arc`-backed, so
+    // cloning it at the call site is cheap.
+    let name = DNSNameRef::try_from_ascii_str(hostname)
+        .map(|name| name.to_owned())
+        .map_err(|_| invalid_data("invalid TLS hostname"));
+
+    future::result(name)
+        .and_then(move |name| connector.connect(name.as_ref(), sock))
+        .map(|s| Box::new(s) as BoxStream)
+}